@@ -1,7 +1,11 @@
-//! Process scheduler statistics from `/proc/[pid]/schedstat`.
+//! Process and system-wide scheduler statistics from `/proc/[pid]/schedstat`
+//! and `/proc/schedstat`.
 
-use std::fs::File;
-use std::io::Result;
+use std::cmp;
+use std::fs::{self, File};
+use std::io::{Result, Seek, SeekFrom};
+use std::str;
+use std::time::Duration;
 
 use libc::pid_t;
 use nom::{space, line_ending};
@@ -19,6 +23,54 @@ pub struct Schedstat {
     pub pcount: usize,
 }
 
+impl Schedstat {
+    /// Returns the average time spent waiting on a runqueue per timeslice
+    /// run (in ns), or `None` if no timeslices have run yet.
+    ///
+    /// Since CFS (kernel 2.6.23) these fields are nanoseconds, not jiffies
+    /// as older kernel documentation claimed; see `version`.
+    pub fn avg_wait_per_slice(&self) -> Option<f64> {
+        if self.pcount == 0 {
+            None
+        } else {
+            Some(self.run_delay as f64 / self.pcount as f64)
+        }
+    }
+
+    /// Returns the time spent running on the CPU.
+    pub fn exec_runtime(&self) -> Duration {
+        Duration::from_nanos(self.sum_exec_runtime as u64)
+    }
+
+    /// Returns the time spent waiting on a runqueue.
+    pub fn run_delay_duration(&self) -> Duration {
+        Duration::from_nanos(self.run_delay as u64)
+    }
+
+    /// Computes the monotonic difference between this (later) sample and an
+    /// earlier one taken from the same process or thread, saturating at
+    /// zero if a counter appears to have reset.
+    pub fn delta(&self, earlier: &Schedstat) -> SchedstatDelta {
+        SchedstatDelta {
+            sum_exec_runtime: self.sum_exec_runtime.saturating_sub(earlier.sum_exec_runtime),
+            run_delay: self.run_delay.saturating_sub(earlier.run_delay),
+            pcount: self.pcount.saturating_sub(earlier.pcount),
+        }
+    }
+}
+
+/// The monotonic difference between two `Schedstat` samples, suitable for
+/// computing per-interval scheduler-latency rates.
+#[derive(Debug, Default, PartialEq, Eq, Hash)]
+pub struct SchedstatDelta {
+    /// Change in time spent running on the CPU (in ns).
+    pub sum_exec_runtime: usize,
+    /// Change in time spent waiting on a runqueue (in ns).
+    pub run_delay: usize,
+    /// Change in number of timeslices run.
+    pub pcount: usize,
+}
+
 /// Parses the schedstat file format.
 named!(parse_schedstat<Schedstat>,
     chain!(sum_exec_runtime: parse_usize ~ space ~
@@ -28,10 +80,15 @@ named!(parse_schedstat<Schedstat>,
                             run_delay: run_delay,
                             pcount: pcount } }));
 
+/// Parses the provided schedstat file, using the provided buffer.
+fn schedstat_file_buf(file: &mut File, buf: &mut [u8]) -> Result<Schedstat> {
+    map_result(parse_schedstat(try!(read_to_end(file, buf))))
+}
+
 /// Parses the provided schedstat file.
 fn schedstat_file(file: &mut File) -> Result<Schedstat> {
     let mut buf = [0; 256];
-    map_result(parse_schedstat(try!(read_to_end(file, &mut buf))))
+    schedstat_file_buf(file, &mut buf)
 }
 
 /// Returns scheduler information for the process with the provided pid.
@@ -49,10 +106,274 @@ pub fn schedstat_task(process_id: pid_t, thread_id: pid_t) -> Result<Schedstat>
     schedstat_file(&mut try!(File::open(&format!("/proc/{}/task/{}/schedstat", process_id, thread_id))))
 }
 
+/// A reusable reader for scanning scheduler statistics across many pids and
+/// threads without re-allocating a parse buffer per call.
+pub struct SchedstatReader {
+    buf: [u8; 256],
+}
+
+impl SchedstatReader {
+    /// Creates a new `SchedstatReader`.
+    pub fn new() -> SchedstatReader {
+        SchedstatReader { buf: [0; 256] }
+    }
+
+    /// Returns scheduler information for the process with the provided pid.
+    pub fn read_pid(&mut self, pid: pid_t) -> Result<Schedstat> {
+        let mut file = try!(File::open(&format!("/proc/{}/schedstat", pid)));
+        schedstat_file_buf(&mut file, &mut self.buf)
+    }
+
+    /// Returns scheduler information from the thread with the provided
+    /// parent process ID and thread ID.
+    pub fn read_task(&mut self, pid: pid_t, tid: pid_t) -> Result<Schedstat> {
+        let mut file = try!(File::open(&format!("/proc/{}/task/{}/schedstat", pid, tid)));
+        schedstat_file_buf(&mut file, &mut self.buf)
+    }
+
+    /// Returns an iterator over every thread of the process with the
+    /// provided pid, yielding each thread's ID and scheduler statistics
+    /// while reusing this reader's buffer.
+    pub fn all_tasks(&mut self, pid: pid_t) -> Result<AllTasks> {
+        let dir = try!(fs::read_dir(format!("/proc/{}/task", pid)));
+        Ok(AllTasks { reader: self, pid: pid, dir: dir })
+    }
+}
+
+/// An iterator over the scheduler statistics of every thread in a process,
+/// produced by `SchedstatReader::all_tasks`.
+pub struct AllTasks<'a> {
+    reader: &'a mut SchedstatReader,
+    pid: pid_t,
+    dir: fs::ReadDir,
+}
+
+impl<'a> Iterator for AllTasks<'a> {
+    type Item = Result<(pid_t, Schedstat)>;
+
+    fn next(&mut self) -> Option<Result<(pid_t, Schedstat)>> {
+        loop {
+            match self.dir.next() {
+                None => return None,
+                Some(Err(error)) => return Some(Err(error)),
+                Some(Ok(entry)) => {
+                    let tid = match entry.file_name().to_str().and_then(|name| name.parse().ok()) {
+                        Some(tid) => tid,
+                        None => continue,
+                    };
+                    return Some(self.reader.read_task(self.pid, tid).map(|stat| (tid, stat)));
+                }
+            }
+        }
+    }
+}
+
+/// The characters permitted in a `domainN` line's cpumask. The kernel's
+/// `%*pb` bitmap formatter prints masks wider than 32 bits as comma-joined
+/// 8-hex-digit groups (e.g. `"00000000,ffffffff"`) once there are more than
+/// 32 logical CPUs, so `,` must be accepted alongside hex digits.
+const HEX_CHARS: &'static str = "0123456789abcdefABCDEF,";
+
+/// A single CPU's scheduling statistics, from the system-wide schedstat file.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct CpuSchedstat {
+    /// The CPU number.
+    pub cpu: usize,
+    /// Number of times `sched_yield()` was called on this CPU.
+    pub yield_count: usize,
+    /// Number of times `schedule()` was called on this CPU.
+    pub schedule_count: usize,
+    /// Number of times `schedule()` left this CPU idle.
+    pub sched_goidle: usize,
+    /// Number of times `try_to_wake_up()` was called on this CPU.
+    pub ttwu_count: usize,
+    /// Number of times `try_to_wake_up()` woke a task onto the CPU it was
+    /// already running on.
+    pub ttwu_local_count: usize,
+    /// Time spent running tasks on this CPU (in ns).
+    pub running_ns: usize,
+    /// Time spent with runnable tasks waiting on this CPU's runqueue (in ns).
+    pub wait_ns: usize,
+    /// Number of timeslices run on this CPU.
+    pub timeslices: usize,
+}
+
+impl CpuSchedstat {
+    /// Computes the monotonic per-field difference between this (later)
+    /// sample and an earlier one from the same CPU, saturating at zero if
+    /// any counter appears to have reset.
+    pub fn delta(&self, earlier: &CpuSchedstat) -> CpuSchedstatDelta {
+        CpuSchedstatDelta {
+            cpu: self.cpu,
+            yield_count: self.yield_count.saturating_sub(earlier.yield_count),
+            schedule_count: self.schedule_count.saturating_sub(earlier.schedule_count),
+            sched_goidle: self.sched_goidle.saturating_sub(earlier.sched_goidle),
+            ttwu_count: self.ttwu_count.saturating_sub(earlier.ttwu_count),
+            ttwu_local_count: self.ttwu_local_count.saturating_sub(earlier.ttwu_local_count),
+            running_ns: self.running_ns.saturating_sub(earlier.running_ns),
+            wait_ns: self.wait_ns.saturating_sub(earlier.wait_ns),
+            timeslices: self.timeslices.saturating_sub(earlier.timeslices),
+        }
+    }
+}
+
+/// The monotonic difference between two `CpuSchedstat` samples from the same
+/// CPU, suitable for computing per-interval scheduler-latency rates.
+#[derive(Debug, Default, PartialEq, Eq, Hash)]
+pub struct CpuSchedstatDelta {
+    /// The CPU number.
+    pub cpu: usize,
+    /// Change in `sched_yield()` calls.
+    pub yield_count: usize,
+    /// Change in `schedule()` calls.
+    pub schedule_count: usize,
+    /// Change in times `schedule()` left this CPU idle.
+    pub sched_goidle: usize,
+    /// Change in `try_to_wake_up()` calls.
+    pub ttwu_count: usize,
+    /// Change in local `try_to_wake_up()` calls.
+    pub ttwu_local_count: usize,
+    /// Change in time spent running tasks on this CPU (in ns).
+    pub running_ns: usize,
+    /// Change in time spent waiting on this CPU's runqueue (in ns).
+    pub wait_ns: usize,
+    /// Change in number of timeslices run on this CPU.
+    pub timeslices: usize,
+}
+
+/// A single scheduling domain's statistics, belonging to the CPU it follows
+/// in the schedstat file.
+#[derive(Debug, Default, PartialEq, Eq, Hash)]
+pub struct DomainSchedstat {
+    /// The domain number, local to the owning CPU.
+    pub domain: usize,
+    /// The domain's CPU mask, as printed by the kernel (e.g. `"00000001"`).
+    pub cpumask: String,
+    /// The domain's remaining whitespace-separated fields, in file order.
+    pub fields: Vec<usize>,
+}
+
+/// System-wide scheduler statistics from `/proc/schedstat`.
+#[derive(Debug, Default, PartialEq, Eq, Hash)]
+pub struct SystemSchedstat {
+    /// The schedstat file format version.
+    pub version: usize,
+    /// Kernel timestamp (in jiffies) at which the file was generated.
+    pub timestamp: usize,
+    /// Per-CPU scheduling statistics, in file order.
+    pub cpus: Vec<CpuSchedstat>,
+    /// Per-CPU scheduling-domain statistics, in file order, indexed the same
+    /// as `cpus`.
+    pub domains: Vec<Vec<DomainSchedstat>>,
+}
+
+/// Parses the `version N` header line.
+named!(parse_schedstat_version<usize>,
+    chain!(tag!("version") ~ space ~ version: parse_usize ~ line_ending,
+           || version));
+
+/// Parses the `timestamp N` header line.
+named!(parse_schedstat_timestamp<usize>,
+    chain!(tag!("timestamp") ~ space ~ timestamp: parse_usize ~ line_ending,
+           || timestamp));
+
+/// Parses a `cpuN <fields...>` line: yield, schedule and wakeup counts,
+/// followed by the three timing fields. The field after `yield_count` is a
+/// legacy always-zero counter the kernel no longer uses, and is discarded.
+named!(parse_cpu_schedstat<CpuSchedstat>,
+    chain!(tag!("cpu") ~ cpu: parse_usize ~ space ~
+           yield_count: parse_usize       ~ space ~
+           parse_usize                    ~ space ~
+           schedule_count: parse_usize    ~ space ~
+           sched_goidle: parse_usize      ~ space ~
+           ttwu_count: parse_usize        ~ space ~
+           ttwu_local_count: parse_usize  ~ space ~
+           running_ns: parse_usize        ~ space ~
+           wait_ns: parse_usize           ~ space ~
+           timeslices: parse_usize        ~ line_ending,
+           || CpuSchedstat {
+               cpu: cpu,
+               yield_count: yield_count,
+               schedule_count: schedule_count,
+               sched_goidle: sched_goidle,
+               ttwu_count: ttwu_count,
+               ttwu_local_count: ttwu_local_count,
+               running_ns: running_ns,
+               wait_ns: wait_ns,
+               timeslices: timeslices,
+           }));
+
+/// Parses a `domainN <cpumask> <fields...>` line.
+named!(parse_domain_schedstat<DomainSchedstat>,
+    chain!(tag!("domain") ~ domain: parse_usize ~ space ~
+           cpumask: map_res!(is_a!(HEX_CHARS), str::from_utf8) ~
+           fields: many1!(preceded!(space, parse_usize)) ~ line_ending,
+           || DomainSchedstat {
+               domain: domain,
+               cpumask: cpumask.to_owned(),
+               fields: fields,
+           }));
+
+/// Parses one `cpuN` line together with the `domainN` lines that follow it.
+named!(parse_cpu_block<(CpuSchedstat, Vec<DomainSchedstat>)>,
+    chain!(cpu: parse_cpu_schedstat ~
+           domains: many0!(parse_domain_schedstat),
+           || (cpu, domains)));
+
+/// Parses the system-wide schedstat file format.
+named!(parse_system_schedstat<SystemSchedstat>,
+    chain!(version: parse_schedstat_version ~
+           timestamp: parse_schedstat_timestamp ~
+           blocks: many1!(parse_cpu_block),
+           || {
+               let (cpus, domains) = blocks.into_iter().unzip();
+               SystemSchedstat { version: version, timestamp: timestamp, cpus: cpus, domains: domains }
+           }));
+
+/// Upper bound on how large a buffer `schedstat_system_file` will grow to
+/// before giving up.
+const MAX_SCHEDSTAT_BUF: usize = 1 << 20;
+
+/// Parses the provided system-wide schedstat file, growing the read buffer
+/// until it fits. `/proc/schedstat` routinely exceeds a few KB on large
+/// multi-socket/NUMA hosts with many CPUs and scheduling domains, and like
+/// most `/proc` files it reports a stat size of zero, so neither a fixed
+/// buffer nor one sized from `fs::metadata` alone can be trusted to fit it
+/// up front.
+fn schedstat_system_file(file: &mut File) -> Result<SystemSchedstat> {
+    let mut len = cmp::max(try!(file.metadata()).len() as usize, 16384);
+    loop {
+        let mut buf = vec![0; len];
+        match read_to_end(file, &mut buf) {
+            Ok(bytes) => return map_result(parse_system_schedstat(bytes)),
+            Err(error) => {
+                if len >= MAX_SCHEDSTAT_BUF {
+                    return Err(error);
+                }
+                try!(file.seek(SeekFrom::Start(0)));
+                len *= 2;
+            }
+        }
+    }
+}
+
+/// Returns system-wide scheduler statistics for every CPU and scheduling
+/// domain, from `/proc/schedstat`.
+pub fn schedstat_system() -> Result<SystemSchedstat> {
+    schedstat_system_file(&mut try!(File::open("/proc/schedstat")))
+}
+
+/// Returns the `/proc/schedstat` format version understood by the running
+/// kernel, so callers can branch on which fields are meaningful.
+pub fn version() -> Result<usize> {
+    Ok(try!(schedstat_system()).version)
+}
+
 #[cfg(test)]
 mod tests {
     use parsers::tests::unwrap;
-    use super::{parse_schedstat, schedstat, schedstat_self};
+    use super::{CpuSchedstat, Schedstat, SchedstatReader, parse_schedstat, parse_system_schedstat,
+                schedstat, schedstat_self, schedstat_system, version};
 
     /// Test that the system schedstat files can be parsed.
     #[test]
@@ -69,6 +390,107 @@ mod tests {
         assert_eq!(1936831953, schedstat.run_delay);
         assert_eq!(8028005, schedstat.pcount);
     }
+
+    #[test]
+    fn test_avg_wait_per_slice() {
+        let schedstat = Schedstat { sum_exec_runtime: 0, run_delay: 100, pcount: 4 };
+        assert_eq!(Some(25f64), schedstat.avg_wait_per_slice());
+
+        let idle = Schedstat::default();
+        assert_eq!(None, idle.avg_wait_per_slice());
+    }
+
+    #[test]
+    fn test_schedstat_delta() {
+        let earlier = Schedstat { sum_exec_runtime: 100, run_delay: 10, pcount: 2 };
+        let later = Schedstat { sum_exec_runtime: 150, run_delay: 5, pcount: 2 };
+        let delta = later.delta(&earlier);
+        assert_eq!(50, delta.sum_exec_runtime);
+        assert_eq!(0, delta.run_delay);
+        assert_eq!(0, delta.pcount);
+    }
+
+    #[test]
+    fn test_cpu_schedstat_delta() {
+        let earlier = CpuSchedstat {
+            cpu: 0,
+            yield_count: 1,
+            schedule_count: 10,
+            sched_goidle: 3,
+            ttwu_count: 20,
+            ttwu_local_count: 5,
+            running_ns: 1000,
+            wait_ns: 100,
+            timeslices: 4,
+        };
+        let later = CpuSchedstat { schedule_count: 15, running_ns: 1500, ..earlier };
+        let delta = later.delta(&earlier);
+        assert_eq!(0, delta.cpu);
+        assert_eq!(5, delta.schedule_count);
+        assert_eq!(500, delta.running_ns);
+        assert_eq!(0, delta.yield_count);
+    }
+
+    /// Test that `SchedstatReader` can scan a pid and all of its tasks while
+    /// reusing its buffer.
+    #[test]
+    fn test_schedstat_reader() {
+        let mut reader = SchedstatReader::new();
+        reader.read_pid(1).unwrap();
+        for task in reader.all_tasks(1).unwrap() {
+            task.unwrap();
+        }
+    }
+
+    /// Test that the running kernel's schedstat version can be read.
+    #[test]
+    fn test_version() {
+        version().unwrap();
+    }
+
+    /// Test that the system-wide schedstat file can be parsed.
+    #[test]
+    fn test_schedstat_system() {
+        schedstat_system().unwrap();
+    }
+
+    #[test]
+    fn test_parse_system_schedstat() {
+        let text = b"version 15\n\
+                     timestamp 4329093789\n\
+                     cpu0 0 0 0 0 0 0 94463559285 22633004012 52767496\n\
+                     domain0 00000001 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n\
+                     cpu1 0 0 0 0 0 0 91263559285 21633004012 51767496\n\
+                     domain0 00000002 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n";
+        let system = unwrap(parse_system_schedstat(text));
+        assert_eq!(15, system.version);
+        assert_eq!(4329093789, system.timestamp);
+        assert_eq!(2, system.cpus.len());
+        assert_eq!(0, system.cpus[0].cpu);
+        assert_eq!(94463559285, system.cpus[0].running_ns);
+        assert_eq!(22633004012, system.cpus[0].wait_ns);
+        assert_eq!(52767496, system.cpus[0].timeslices);
+        assert_eq!(1, system.domains[0].len());
+        assert_eq!("00000001", system.domains[0][0].cpumask);
+        assert_eq!(1, system.cpus[1].cpu);
+        assert_eq!(0, system.cpus[0].yield_count);
+        assert_eq!(0, system.cpus[0].schedule_count);
+        assert_eq!(0, system.cpus[0].sched_goidle);
+        assert_eq!(0, system.cpus[0].ttwu_count);
+        assert_eq!(0, system.cpus[0].ttwu_local_count);
+    }
+
+    /// Test that comma-joined cpumask groups (as printed by `%*pb` on
+    /// systems with more than 32 logical CPUs) are parsed correctly.
+    #[test]
+    fn test_parse_system_schedstat_wide_cpumask() {
+        let text = b"version 15\n\
+                     timestamp 4329093789\n\
+                     cpu0 0 0 0 0 0 0 94463559285 22633004012 52767496\n\
+                     domain0 00000000,ffffffff 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n";
+        let system = unwrap(parse_system_schedstat(text));
+        assert_eq!("00000000,ffffffff", system.domains[0][0].cpumask);
+    }
 }
 
 #[cfg(all(test, rustc_nightly))]
@@ -0,0 +1,110 @@
+//! System-wide Pressure Stall Information from `/proc/pressure/{cpu,io,memory}`
+//! (kernel 4.20+, `CONFIG_PSI`).
+
+use std::fs::File;
+use std::io::Result;
+
+use nom::{space, line_ending};
+
+use parsers::{map_result, parse_f32, parse_usize, read_to_end};
+
+/// A single `some`/`full` pressure-stall line: the share of wall time
+/// stalled on a resource over the trailing 10/60/300-second windows, and
+/// the cumulative stall time.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct PressureLine {
+    /// Percentage of time stalled over the last 10 seconds.
+    pub avg10: f32,
+    /// Percentage of time stalled over the last 60 seconds.
+    pub avg60: f32,
+    /// Percentage of time stalled over the last 300 seconds.
+    pub avg300: f32,
+    /// Cumulative stall time (in us).
+    pub total: u64,
+}
+
+/// Pressure Stall Information for a single resource.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct Pressure {
+    /// Stall stats for tasks stalled on this resource, whether or not other
+    /// tasks are able to run.
+    pub some: PressureLine,
+    /// Stall stats for tasks stalled on this resource while no task wanting
+    /// it is able to run. `None` when the file has no `full` line, as with
+    /// `cpu` on kernels before 5.13.
+    pub full: Option<PressureLine>,
+}
+
+/// Parses a `some avg10=.. avg60=.. avg300=.. total=..` or `full ...` line.
+named!(parse_pressure_line<PressureLine>,
+    chain!(tag!("avg10=")  ~ avg10: parse_f32  ~ space ~
+           tag!("avg60=")  ~ avg60: parse_f32  ~ space ~
+           tag!("avg300=") ~ avg300: parse_f32 ~ space ~
+           tag!("total=")  ~ total: parse_usize ~ line_ending,
+           || PressureLine { avg10: avg10, avg60: avg60, avg300: avg300, total: total as u64 }));
+
+/// Parses the pressure file format: a `some` line, and (for `io`/`memory`)
+/// a trailing `full` line. The `full` line is wrapped in `complete!()`
+/// since it may be entirely absent (pre-5.13 `cpu` files have no `full`
+/// line), and without it `tag!("full")` can't tell "no more input" from
+/// "not enough input yet" and `opt!` never converts that into `None`.
+named!(parse_pressure<Pressure>,
+    chain!(tag!("some") ~ space ~ some: parse_pressure_line ~
+           full: opt!(complete!(chain!(tag!("full") ~ space ~ line: parse_pressure_line, || line))),
+           || Pressure { some: some, full: full }));
+
+/// Parses the provided pressure file.
+fn pressure_file(file: &mut File) -> Result<Pressure> {
+    let mut buf = [0; 256];
+    map_result(parse_pressure(try!(read_to_end(file, &mut buf))))
+}
+
+/// Returns CPU pressure stall information from `/proc/pressure/cpu`.
+pub fn pressure_cpu() -> Result<Pressure> {
+    pressure_file(&mut try!(File::open("/proc/pressure/cpu")))
+}
+
+/// Returns I/O pressure stall information from `/proc/pressure/io`.
+pub fn pressure_io() -> Result<Pressure> {
+    pressure_file(&mut try!(File::open("/proc/pressure/io")))
+}
+
+/// Returns memory pressure stall information from `/proc/pressure/memory`.
+pub fn pressure_memory() -> Result<Pressure> {
+    pressure_file(&mut try!(File::open("/proc/pressure/memory")))
+}
+
+#[cfg(test)]
+mod tests {
+    use parsers::tests::unwrap;
+    use super::{parse_pressure, pressure_cpu, pressure_io, pressure_memory};
+
+    /// Test that the system pressure files can be parsed.
+    #[test]
+    fn test_pressure() {
+        pressure_cpu().unwrap();
+        pressure_io().unwrap();
+        pressure_memory().unwrap();
+    }
+
+    #[test]
+    fn test_parse_pressure_cpu() {
+        let text = b"some avg10=0.00 avg60=0.05 avg300=1.23 total=445678\n";
+        let pressure = unwrap(parse_pressure(text));
+        assert_eq!(0.00, pressure.some.avg10);
+        assert_eq!(0.05, pressure.some.avg60);
+        assert_eq!(1.23, pressure.some.avg300);
+        assert_eq!(445678, pressure.some.total);
+        assert_eq!(None, pressure.full);
+    }
+
+    #[test]
+    fn test_parse_pressure_io() {
+        let text = b"some avg10=0.00 avg60=0.00 avg300=0.00 total=0\n\
+                     full avg10=0.00 avg60=0.00 avg300=0.12 total=123\n";
+        let pressure = unwrap(parse_pressure(text));
+        let full = pressure.full.unwrap();
+        assert_eq!(0.12, full.avg300);
+        assert_eq!(123, full.total);
+    }
+}